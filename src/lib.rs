@@ -1,219 +1,255 @@
+mod list;
+pub use list::{ListPool, ListRef};
+
 /// Represents a Pool object, where you can preallocate a grid
 /// of Data preemptively.
 ///
-/// The Pool has an internal linked list to free items, where
-/// you can check the indexes and insert at those.
+/// Free slots are tracked as a LIFO stack of indexes: freeing a slot
+/// pushes its index, and allocating pops the most recently freed one.
+/// Both are O(1), since a slot only needs an occupied/vacant tag and no
+/// prev/next bookkeeping. The one exception is `set(idx, ..)` with a
+/// caller-chosen `idx` that isn't the top of the stack, which still has
+/// to scan the stack to find and remove it. Because it's LIFO rather
+/// than lowest-index-first, `free_indexes()` walks slots in the order
+/// they'd be popped (most recently freed first), not numeric order.
+///
+/// Slots are generational: every time a slot is freed its generation is
+/// bumped, so a `Handle` obtained before the free no longer matches what's
+/// stored there and `entry`/`entry_mut` report it as gone instead of
+/// silently returning whatever was written into the reused slot.
 ///
-/// Usually, inserting should be pretty cheap and removing should be O(k)
-/// where k is the number of free items.
+/// `Pool<T>` is generic over the stored payload, so it can hold anything
+/// (game entities, parsed nodes, connection objects, ...) rather than
+/// forcing callers to serialize into `Vec<u8>`. `T` needs no `Default`
+/// bound, since empty slots simply don't hold a payload.
 ///
-/// There are ways to optimize this, I believe, but since this was just an
-/// exercise, I just left this as is.
-pub struct Pool {
-    entries: Vec<PoolEntry>,
-    first_free: Option<usize>,
+/// The backing storage only ever grows: `reserve` (and `new_id`, once the
+/// free stack runs dry) append fresh empty slots rather than failing, and
+/// freed slots are recycled through the free stack instead of shrinking
+/// the Vec.
+pub struct Pool<T> {
+    entries: Vec<PoolEntry<T>>,
+    free_stack: Vec<usize>,
 }
 
-impl Pool {
+impl<T> Pool<T> {
     /// Create a new Pool with N allocations.
-    pub fn new(size: usize) -> Pool {
-        let entries = (0..size).map(|idx| {
-            if idx == 0 {
-                PoolEntry::Empty(EmptyEntry::new(None, Some(idx + 1)))
-            } else if idx == size - 1 {
-                PoolEntry::Empty(EmptyEntry::new(Some(idx - 1), None))
-            } else {
-                PoolEntry::Empty(EmptyEntry::new(Some(idx - 1), Some(idx + 1)))
-            }
-        });
+    pub fn new(size: usize) -> Pool<T> {
+        let entries = (0..size)
+            .map(|_| PoolEntry::Empty(EmptyEntry::new(0)))
+            .collect();
+
+        // Pushed in descending order, so a pristine pool pops (and
+        // iterates) its slots starting from index 0.
+        let free_stack = (0..size).rev().collect();
+
         Pool {
-            entries: entries.collect(),
-            first_free: Some(0),
+            entries,
+            free_stack,
         }
     }
 
-    /// Get the index of the first free element.
+    /// Get the index that would be allocated next.
     pub fn first_free(&self) -> Option<usize> {
-        self.first_free
+        self.free_stack.last().copied()
     }
 
-    /// Get a particular entry.
-    pub fn entry(&self, idx: usize) -> Option<&PoolEntry> {
-        self.entries.get(idx)
+    /// Current capacity of the Pool, i.e. the number of slots it holds
+    /// regardless of whether they're occupied.
+    pub fn len(&self) -> usize {
+        self.entries.len()
     }
 
-    /// Insert an item into the Pool, it will not crash on overflowing items, just ignore the request.
-    pub fn set(&mut self, idx: usize, data: FilledEntry) -> Option<usize> {
-        if idx >= self.entries.len() {
-            return None;
-        }
+    /// Alias for [`Pool::len`].
+    pub fn maximum(&self) -> usize {
+        self.len()
+    }
 
-        let (prev, next) = match self.entries.get(idx).unwrap() {
-            PoolEntry::Data(_) => {
-                // There was data here before, noop on the empty linked-list.
-                self.entries[idx] = PoolEntry::Data(data);
-                return Some(idx);
-            }
-            PoolEntry::Empty(current_node) => (current_node.prev, current_node.next),
-        };
+    /// Returns `true` if the Pool has no slots at all.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
 
-        if Some(idx) == self.first_free {
-            self.first_free = next;
+    /// Grow the backing storage by `additional` slots and push them onto
+    /// the free stack so subsequent allocations can use them. The Vec
+    /// only ever grows; freed entries are recycled rather than shrinking
+    /// it.
+    pub fn reserve(&mut self, additional: usize) {
+        if additional == 0 {
+            return;
         }
 
-        if let Some(PoolEntry::Empty(prev_node)) = prev.map(|prev_id| {
-            self.entries
-                .get_mut(prev_id)
-                .expect("Prev should always be valid.")
-        }) {
-            prev_node.next = next;
-        }
-        if let Some(PoolEntry::Empty(next_node)) = next.map(|next_id| {
-            self.entries
-                .get_mut(next_id)
-                .expect("Next should always be valid.")
-        }) {
-            next_node.prev = prev;
+        let old_len = self.entries.len();
+        self.entries
+            .extend((0..additional).map(|_| PoolEntry::Empty(EmptyEntry::new(0))));
+        self.free_stack.extend((old_len..old_len + additional).rev());
+    }
+
+    /// Get a particular entry, as long as `handle`'s generation still
+    /// matches what's stored in the slot.
+    pub fn entry(&self, handle: Handle) -> Option<&FilledEntry<T>> {
+        match self.entries.get(handle.index) {
+            Some(PoolEntry::Data(data)) if data.generation == handle.generation => Some(data),
+            _ => None,
         }
+    }
 
-        self.entries[idx] = PoolEntry::Data(data);
+    /// Mutable variant of [`Pool::entry`].
+    pub fn entry_mut(&mut self, handle: Handle) -> Option<&mut FilledEntry<T>> {
+        match self.entries.get_mut(handle.index) {
+            Some(PoolEntry::Data(data)) if data.generation == handle.generation => Some(data),
+            _ => None,
+        }
+    }
 
-        Some(idx)
+    /// Read a slot by raw index, without checking a handle's generation.
+    ///
+    /// Crate-internal escape hatch for allocators (like [`crate::list`])
+    /// that manage their own compact, generation-oblivious indices and
+    /// accept the aliasing risk in exchange for not paying for a
+    /// generation on every reference.
+    pub(crate) fn entry_raw(&self, idx: usize) -> Option<&T> {
+        match self.entries.get(idx) {
+            Some(PoolEntry::Data(data)) => Some(&data.inner),
+            _ => None,
+        }
     }
 
-    /// Free a certain item from the Pool.
-    pub fn free(&mut self, idx: usize) -> Option<usize> {
+    /// Insert an item into the Pool, it will not crash on overflowing items, just ignore the request.
+    pub fn set(&mut self, idx: usize, data: T) -> Option<Handle> {
         if idx >= self.entries.len() {
             return None;
         }
 
-        match self.entries.get_mut(idx).unwrap() {
-            PoolEntry::Empty(_) => (),
-            PoolEntry::Data(_) => {
-                self.insert_on_free_list(idx);
+        let generation = match &self.entries[idx] {
+            PoolEntry::Data(current) => {
+                // There was data here before, noop on the free stack.
+                let generation = current.generation;
+                self.entries[idx] = PoolEntry::Data(FilledEntry {
+                    inner: data,
+                    generation,
+                });
+                return Some(Handle {
+                    index: idx,
+                    generation,
+                });
             }
+            PoolEntry::Empty(current) => current.generation,
         };
 
-        Some(idx)
-    }
+        self.remove_from_free_stack(idx);
+        self.entries[idx] = PoolEntry::Data(FilledEntry {
+            inner: data,
+            generation,
+        });
 
-    /// To insert on the FreeList we either:
-    /// - If this node will be inserted before first_free, replace first_free and add
-    /// current first_free as new node next.
-    ///
-    /// - If this node will be inserted after first_free, it will replace a next on some
-    /// node after (or including first_free).
-    fn insert_on_free_list(&mut self, insert_index: usize) {
-        let first_free_index = match self.first_free {
-            Some(f_idx) => f_idx,
-            None => {
-                self.first_free = Some(insert_index);
-                self.entries[insert_index] = PoolEntry::Empty(EmptyEntry::new(None, None));
-                return;
-            }
-        };
+        Some(Handle {
+            index: idx,
+            generation,
+        })
+    }
 
-        if insert_index == first_free_index {
-            return;
+    /// Free a certain item from the Pool, bumping its generation so that
+    /// any `Handle` issued for it stops resolving.
+    pub fn free(&mut self, idx: usize) -> Option<usize> {
+        if idx >= self.entries.len() {
+            return None;
         }
 
-        if insert_index < first_free_index {
-            if let Some(PoolEntry::Empty(empty)) = self.entries.get_mut(first_free_index) {
-                empty.prev = Some(insert_index);
-            }
-            self.first_free = Some(insert_index);
-            self.entries[insert_index] =
-                PoolEntry::Empty(EmptyEntry::new(None, Some(first_free_index)));
-            return;
+        if let PoolEntry::Data(data) = &self.entries[idx] {
+            let next_generation = data.generation.wrapping_add(1);
+            self.entries[idx] = PoolEntry::Empty(EmptyEntry::new(next_generation));
+            self.free_stack.push(idx);
         }
 
-        let mut current_idx = first_free_index;
-        while let Some(PoolEntry::Empty(empty)) = self.entries.get_mut(current_idx) {
-            match empty.next {
-                Some(n_idx) => {
-                    if n_idx > insert_index {
-                        break;
-                    } else {
-                        current_idx = n_idx;
-                    }
-                }
-                None => break,
-            }
+        Some(idx)
+    }
+
+    /// Remove `idx` from wherever it sits in the free stack. Unlike a
+    /// push or a pop, this isn't O(1): freeing is always a push onto the
+    /// top, but `set` lets a caller occupy any index, so the index has to
+    /// be found first.
+    fn remove_from_free_stack(&mut self, idx: usize) {
+        if let Some(pos) = self.free_stack.iter().rposition(|&free_idx| free_idx == idx) {
+            self.free_stack.remove(pos);
         }
+    }
 
-        let former_next =
-            if let Some(PoolEntry::Empty(insert_node)) = self.entries.get_mut(current_idx) {
-                let v = insert_node.next;
-                insert_node.next = Some(insert_index);
+    /// Allocate the most recently freed index (or the next virgin one),
+    /// store `data` there and return its handle. Growing the backing
+    /// storage when the free stack is empty means this never fails,
+    /// unlike `set` with a caller-picked index.
+    pub fn new_id(&mut self, data: T) -> Handle {
+        let idx = match self.first_free() {
+            Some(idx) => idx,
+            None => {
+                self.reserve(1);
+                self.first_free().expect("just grew the pool by one slot")
+            }
+        };
 
-                v
-            } else {
-                panic!("Should never happen");
-            };
+        self.set(idx, data)
+            .expect("a freshly allocated index is always in bounds")
+    }
 
-        self.entries[insert_index] =
-            PoolEntry::Empty(EmptyEntry::new(Some(current_idx), former_next));
+    /// Alias for [`Pool::free`], matching the `new_id`/`return_id`
+    /// allocator-style naming.
+    pub fn return_id(&mut self, idx: usize) -> Option<usize> {
+        self.free(idx)
     }
 
-    /// Get an iterator over the free or empty indexes.
-    pub fn free_indexes(&self) -> PoolFreeIterator {
+    /// Get an iterator over the free or empty indexes, in the order
+    /// they'd be popped by allocation (most recently freed first).
+    pub fn free_indexes(&self) -> PoolFreeIterator<'_> {
         PoolFreeIterator {
-            pool: self,
-            current_index: self.first_free,
+            remaining: self.free_stack.iter(),
         }
     }
 }
 
-/// Iterator over the free items of a Pool.
+/// A handle to a slot in a [`Pool`], tying an index to the generation that
+/// was live when the handle was issued. A handle whose generation no longer
+/// matches the slot (because it was freed and possibly reused) is stale and
+/// resolves to `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Handle {
+    pub index: usize,
+    pub generation: u32,
+}
+
+/// Iterator over the free or empty indexes of a Pool, in pop order.
 pub struct PoolFreeIterator<'s> {
-    pool: &'s Pool,
-    current_index: Option<usize>,
+    remaining: std::slice::Iter<'s, usize>,
 }
 
 impl<'s> Iterator for PoolFreeIterator<'s> {
     type Item = usize;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let current_index = match self.current_index {
-            Some(i) => i,
-            None => return None,
-        };
-        match self.pool.entry(current_index) {
-            Some(PoolEntry::Empty(entry)) => {
-                let v = Some(current_index);
-                if self.current_index == entry.next {
-                    // Stuck in a loop.
-                    return None;
-                }
-                self.current_index = entry.next;
-
-                v
-            }
-            _ => None,
-        }
+        self.remaining.next_back().copied()
     }
 }
 
 #[derive(Debug)]
-pub enum PoolEntry {
-    Data(FilledEntry),
+pub enum PoolEntry<T> {
+    Data(FilledEntry<T>),
     Empty(EmptyEntry),
 }
 
 #[derive(Debug)]
-pub struct FilledEntry {
-    pub inner: Vec<u8>,
+pub struct FilledEntry<T> {
+    pub inner: T,
+    generation: u32,
 }
 
 #[derive(Debug)]
 pub struct EmptyEntry {
-    pub prev: Option<usize>,
-    pub next: Option<usize>,
+    generation: u32,
 }
 
 impl EmptyEntry {
-    pub fn new(prev: Option<usize>, next: Option<usize>) -> EmptyEntry {
-        EmptyEntry { prev, next }
+    pub fn new(generation: u32) -> EmptyEntry {
+        EmptyEntry { generation }
     }
 }
 
@@ -223,14 +259,14 @@ mod tests {
 
     #[test]
     fn test_get_empty() {
-        let pool = Pool::new(12);
+        let pool: Pool<Vec<u8>> = Pool::new(12);
 
         assert_eq!(pool.first_free(), Some(0));
     }
 
     #[test]
     fn test_free_iterator() {
-        let pool = Pool::new(12);
+        let pool: Pool<Vec<u8>> = Pool::new(12);
         let free_indexes: Vec<usize> = pool.free_indexes().collect();
         let all_indexes: Vec<usize> = (0..12).collect();
 
@@ -239,11 +275,11 @@ mod tests {
 
     #[test]
     fn test_insert() {
-        let mut pool = Pool::new(10);
-        pool.set(0, FilledEntry { inner: Vec::new() });
-        pool.set(2, FilledEntry { inner: Vec::new() });
-        pool.set(4, FilledEntry { inner: Vec::new() });
-        pool.set(6, FilledEntry { inner: Vec::new() });
+        let mut pool: Pool<Vec<u8>> = Pool::new(10);
+        pool.set(0, Vec::new());
+        pool.set(2, Vec::new());
+        pool.set(4, Vec::new());
+        pool.set(6, Vec::new());
 
         let free_indexes: Vec<usize> = pool.free_indexes().collect();
         let expected_free: Vec<usize> = vec![1, 3, 5, 7, 8, 9];
@@ -253,41 +289,155 @@ mod tests {
 
     #[test]
     fn test_insert2() {
-        let mut pool = Pool::new(4);
-        pool.set(0, FilledEntry { inner: Vec::new() });
+        let mut pool: Pool<Vec<u8>> = Pool::new(4);
+        pool.set(0, Vec::new());
 
-        assert_eq!(pool.first_free, Some(1));
+        assert_eq!(pool.first_free(), Some(1));
     }
 
     #[test]
     fn test_free() {
-        let mut pool = Pool::new(4);
-        pool.set(0, FilledEntry { inner: Vec::new() });
-        pool.set(2, FilledEntry { inner: Vec::new() });
+        let mut pool: Pool<Vec<u8>> = Pool::new(4);
+        pool.set(0, Vec::new());
+        pool.set(2, Vec::new());
         pool.free(0);
 
         let free_indexes: Vec<usize> = pool.free_indexes().collect();
         let expected_free: Vec<usize> = vec![0, 1, 3];
 
         assert_eq!(free_indexes, expected_free);
-        assert_eq!(pool.first_free, Some(0));
+        assert_eq!(pool.first_free(), Some(0));
     }
 
     #[test]
     fn test_free2() {
-        let mut pool = Pool::new(10);
-        pool.set(0, FilledEntry { inner: Vec::new() });
-        pool.set(2, FilledEntry { inner: Vec::new() });
-        pool.set(4, FilledEntry { inner: Vec::new() });
-        pool.set(6, FilledEntry { inner: Vec::new() });
+        let mut pool: Pool<Vec<u8>> = Pool::new(10);
+        pool.set(0, Vec::new());
+        pool.set(2, Vec::new());
+        pool.set(4, Vec::new());
+        pool.set(6, Vec::new());
 
         pool.free(0);
         pool.free(4);
 
+        // 4 was freed last, so it's the next one popped; the rest still
+        // come out lowest-to-highest since nothing else was re-pushed.
         let free_indexes: Vec<usize> = pool.free_indexes().collect();
-        let expected_free: Vec<usize> = vec![0, 1, 3, 4, 5, 7, 8, 9];
+        let expected_free: Vec<usize> = vec![4, 0, 1, 3, 5, 7, 8, 9];
 
         assert_eq!(free_indexes, expected_free);
-        assert_eq!(pool.first_free, Some(0));
+        assert_eq!(pool.first_free(), Some(4));
+    }
+
+    #[test]
+    fn test_free_reuses_most_recently_freed_index() {
+        let mut pool: Pool<Vec<u8>> = Pool::new(3);
+        pool.set(0, Vec::new());
+        pool.set(1, Vec::new());
+        pool.set(2, Vec::new());
+
+        pool.free(1);
+        pool.free(0);
+
+        // 0 was freed last, so allocation reuses it before 1.
+        assert_eq!(pool.new_id(Vec::new()).index, 0);
+        assert_eq!(pool.new_id(Vec::new()).index, 1);
+    }
+
+    #[test]
+    fn test_handle_goes_stale_after_free() {
+        let mut pool: Pool<Vec<u8>> = Pool::new(4);
+        let handle = pool.set(0, vec![1, 2, 3]).unwrap();
+
+        pool.free(0);
+
+        assert!(pool.entry(handle).is_none());
+
+        let new_handle = pool.set(0, vec![4, 5, 6]).unwrap();
+        assert_ne!(handle.generation, new_handle.generation);
+        assert!(pool.entry(new_handle).is_some());
+        assert!(pool.entry(handle).is_none());
+    }
+
+    #[test]
+    fn test_entry_mut() {
+        let mut pool: Pool<Vec<u8>> = Pool::new(4);
+        let handle = pool.set(0, vec![1]).unwrap();
+
+        pool.entry_mut(handle).unwrap().inner.push(2);
+
+        assert_eq!(pool.entry(handle).unwrap().inner, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_new_id_basic() {
+        let mut pool: Pool<Vec<u8>> = Pool::new(2);
+        let a = pool.new_id(vec![1]);
+        let b = pool.new_id(vec![2]);
+
+        assert_eq!(a.index, 0);
+        assert_eq!(b.index, 1);
+        assert_eq!(pool.entry(a).unwrap().inner, vec![1]);
+        assert_eq!(pool.entry(b).unwrap().inner, vec![2]);
+    }
+
+    #[test]
+    fn test_new_id_grows_when_full() {
+        let mut pool: Pool<Vec<u8>> = Pool::new(1);
+        pool.new_id(vec![1]);
+
+        let grown = pool.new_id(vec![2]);
+
+        assert_eq!(grown.index, 1);
+        assert_eq!(pool.entry(grown).unwrap().inner, vec![2]);
+    }
+
+    #[test]
+    fn test_return_id_frees_slot_for_reuse() {
+        let mut pool: Pool<Vec<u8>> = Pool::new(1);
+        let handle = pool.new_id(vec![1]);
+
+        pool.return_id(handle.index);
+
+        assert!(pool.entry(handle).is_none());
+        let new_handle = pool.new_id(vec![2]);
+        assert_eq!(new_handle.index, handle.index);
+    }
+
+    #[test]
+    fn test_reserve_grows_capacity_and_free_list() {
+        let mut pool: Pool<Vec<u8>> = Pool::new(2);
+        pool.reserve(3);
+
+        assert_eq!(pool.len(), 5);
+        assert_eq!(pool.maximum(), 5);
+
+        // The freshly reserved slots land on top of the stack, so they're
+        // popped (and iterated) before the pool's original free slots.
+        let free_indexes: Vec<usize> = pool.free_indexes().collect();
+        assert_eq!(free_indexes, vec![2, 3, 4, 0, 1]);
+    }
+
+    #[test]
+    fn test_reserve_after_partial_use() {
+        let mut pool: Pool<Vec<u8>> = Pool::new(2);
+        pool.set(0, Vec::new());
+        pool.reserve(2);
+
+        let free_indexes: Vec<usize> = pool.free_indexes().collect();
+        assert_eq!(free_indexes, vec![2, 3, 1]);
+    }
+
+    #[test]
+    fn test_arbitrary_payload() {
+        #[derive(Debug, PartialEq)]
+        struct Entity {
+            hp: i32,
+        }
+
+        let mut pool: Pool<Entity> = Pool::new(4);
+        let handle = pool.set(0, Entity { hp: 10 }).unwrap();
+
+        assert_eq!(pool.entry(handle).unwrap().inner, Entity { hp: 10 });
     }
 }
@@ -0,0 +1,293 @@
+//! Pooled, variable-length lists built on top of [`Pool`].
+//!
+//! Many short-lived or growing lists (adjacency lists, AST child lists,
+//! ...) can share one [`ListPool`] instead of each heap-allocating its own
+//! `Vec`. A [`ListRef`] is a 4-byte index into the pool's backing store;
+//! each list occupies a contiguous run of slots whose first slot holds
+//! the list's current length, and growth steps through power-of-two size
+//! classes, reusing a same-sized run from a previous list when one is
+//! available instead of always appending to the pool's tail.
+//!
+//! `ListRef` carries no generation, unlike [`Handle`](crate::Handle), so
+//! it's only 4 bytes and lists don't need a `Drop` impl -- the whole
+//! structure can be thrown away in O(1) via [`ListPool::clear_all`].
+//! The price is aliasing hazards a generational handle would normally
+//! catch, and they're easy to hit by accident because `ListRef` is
+//! `Copy`:
+//!
+//! - Using a `ListRef` after its pool's `clear_all`, or against a
+//!   different `ListPool`, names whatever now happens to live at that
+//!   index (or nothing) and silently reads garbage instead of `None`.
+//! - **Growing a list relocates it.** [`ListRef::push`] that crosses a
+//!   size-class boundary moves the run to a larger one and frees the old
+//!   run back into the pool for reuse -- by an unrelated list, in the
+//!   *same* pool, with no `clear_all` involved. Any copy of the `ListRef`
+//!   taken before that push (trivial, since it's `Copy`: `let stale = my_list.unwrap();`
+//!   while still pushing through `my_list`) keeps pointing at the old
+//!   run's slots. Once another list reuses them, `stale.get(..)` silently
+//!   reads that other list's live data instead of returning `None` or the
+//!   original value. Treat the `ListRef` stored in your `Option` as the
+//!   only valid copy, and re-read it after every `push` rather than
+//!   holding one from before.
+
+use crate::Pool;
+
+const MIN_CAPACITY: usize = 4;
+
+#[derive(Clone)]
+enum Slot<T> {
+    Header(usize),
+    Value(T),
+}
+
+/// A 4-byte index into a [`ListPool`]'s backing store, naming the length
+/// header of a list's run. See this module's top-level docs for the
+/// aliasing hazards that come with skipping generation checks -- in
+/// particular, a `ListRef` copied out before a [`ListRef::push`] that
+/// grows the list can end up aliasing a different list's data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListRef(u32);
+
+impl ListRef {
+    /// Append `value` to `*list`, allocating the first run the first time
+    /// `list` is `None` and growing to the next power-of-two size class
+    /// when the current run is full.
+    ///
+    /// Growing relocates the list's run and frees the old one for reuse
+    /// by any other list sharing this pool. Any `ListRef` copied out of
+    /// `*list` before this call is invalidated by it -- see this module's
+    /// top-level docs.
+    pub fn push<T: Clone>(list: &mut Option<ListRef>, value: T, pool: &mut ListPool<T>) {
+        let start = match *list {
+            None => pool.allocate_run(MIN_CAPACITY),
+            Some(ListRef(start)) => {
+                let start = start as usize;
+                let len = pool.len_of(start);
+                let capacity = ListPool::<T>::capacity_for_len(len);
+                if len < capacity {
+                    start
+                } else {
+                    pool.grow_run(start, capacity)
+                }
+            }
+        };
+
+        let len = pool.len_of(start);
+        pool.pool.set(start + 1 + len, Slot::Value(value));
+        pool.pool.set(start, Slot::Header(len + 1));
+        *list = Some(ListRef(start as u32));
+    }
+
+    /// Number of elements currently stored in the list.
+    pub fn len<T>(self, pool: &ListPool<T>) -> usize {
+        pool.len_of(self.0 as usize)
+    }
+
+    /// Get the element at `index`, or `None` if it's out of bounds.
+    pub fn get<T>(self, index: usize, pool: &ListPool<T>) -> Option<&T> {
+        if index >= self.len(pool) {
+            return None;
+        }
+        match pool.pool.entry_raw(self.0 as usize + 1 + index) {
+            Some(Slot::Value(value)) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Backing allocator for [`ListRef`] lists. All the actual storage lives
+/// in a single [`Pool`]; `ListPool` only adds the run bookkeeping (length
+/// headers and a size-class free list for reusing runs).
+pub struct ListPool<T> {
+    pool: Pool<Slot<T>>,
+    free_runs: std::collections::BTreeMap<usize, Vec<usize>>,
+}
+
+impl<T> Default for ListPool<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> ListPool<T> {
+    /// Create an empty `ListPool`.
+    pub fn new() -> ListPool<T> {
+        ListPool {
+            pool: Pool::new(0),
+            free_runs: std::collections::BTreeMap::new(),
+        }
+    }
+
+    /// Discard every list at once by resetting the backing pool. Existing
+    /// `ListRef`s into this pool become dangling; using one afterwards is
+    /// an aliasing hazard (see this module's top-level docs), not a panic.
+    pub fn clear_all(&mut self) {
+        *self = ListPool::new();
+    }
+
+    /// Smallest power-of-two size class (at least `MIN_CAPACITY`) whose
+    /// run can hold `len` elements.
+    fn capacity_for_len(len: usize) -> usize {
+        if len <= MIN_CAPACITY {
+            MIN_CAPACITY
+        } else {
+            len.next_power_of_two()
+        }
+    }
+
+    fn len_of(&self, start: usize) -> usize {
+        match self.pool.entry_raw(start) {
+            Some(Slot::Header(len)) => *len,
+            _ => 0,
+        }
+    }
+
+    /// Reserve a fresh run of `capacity` payload slots (plus one header
+    /// slot), reusing a previously freed run of the same size class if
+    /// one is available.
+    fn allocate_run(&mut self, capacity: usize) -> usize {
+        let reused = self
+            .free_runs
+            .get_mut(&capacity)
+            .and_then(|starts| starts.pop());
+
+        let start = match reused {
+            Some(start) => start,
+            None => {
+                let start = self.pool.len();
+                self.pool.reserve(capacity + 1);
+                start
+            }
+        };
+
+        self.pool.set(start, Slot::Header(0));
+        start
+    }
+
+    fn free_run(&mut self, start: usize, capacity: usize) {
+        for offset in 0..=capacity {
+            self.pool.free(start + offset);
+        }
+        self.free_runs.entry(capacity).or_default().push(start);
+    }
+}
+
+impl<T: Clone> ListPool<T> {
+    /// Move a full run to the next size class up, cloning its elements
+    /// across, and return the new run's start index.
+    fn grow_run(&mut self, old_start: usize, old_capacity: usize) -> usize {
+        let new_capacity = old_capacity * 2;
+        let new_start = self.allocate_run(new_capacity);
+        let len = self.len_of(old_start);
+
+        for offset in 1..=len {
+            if let Some(Slot::Value(value)) = self.pool.entry_raw(old_start + offset) {
+                let value = value.clone();
+                self.pool.set(new_start + offset, Slot::Value(value));
+            }
+        }
+        self.pool.set(new_start, Slot::Header(len));
+
+        self.free_run(old_start, old_capacity);
+        new_start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_and_get() {
+        let mut pool: ListPool<i32> = ListPool::new();
+        let mut list = None;
+
+        ListRef::push(&mut list, 1, &mut pool);
+        ListRef::push(&mut list, 2, &mut pool);
+        ListRef::push(&mut list, 3, &mut pool);
+
+        let list = list.unwrap();
+        assert_eq!(list.len(&pool), 3);
+        assert_eq!(list.get(0, &pool), Some(&1));
+        assert_eq!(list.get(1, &pool), Some(&2));
+        assert_eq!(list.get(2, &pool), Some(&3));
+        assert_eq!(list.get(3, &pool), None);
+    }
+
+    #[test]
+    fn test_push_grows_past_a_size_class() {
+        let mut pool: ListPool<i32> = ListPool::new();
+        let mut list = None;
+
+        for i in 0..10 {
+            ListRef::push(&mut list, i, &mut pool);
+        }
+
+        let list = list.unwrap();
+        assert_eq!(list.len(&pool), 10);
+        for i in 0..10 {
+            assert_eq!(list.get(i as usize, &pool), Some(&i));
+        }
+    }
+
+    #[test]
+    fn test_independent_lists_share_one_pool() {
+        let mut pool: ListPool<i32> = ListPool::new();
+        let mut a = None;
+        let mut b = None;
+
+        ListRef::push(&mut a, 1, &mut pool);
+        ListRef::push(&mut b, 10, &mut pool);
+        ListRef::push(&mut a, 2, &mut pool);
+        ListRef::push(&mut b, 20, &mut pool);
+
+        let a = a.unwrap();
+        let b = b.unwrap();
+        assert_eq!(a.get(0, &pool), Some(&1));
+        assert_eq!(a.get(1, &pool), Some(&2));
+        assert_eq!(b.get(0, &pool), Some(&10));
+        assert_eq!(b.get(1, &pool), Some(&20));
+    }
+
+    #[test]
+    fn test_growth_invalidates_a_stale_listref_copy() {
+        // Documents the hazard called out in the module docs: growing a
+        // list relocates its run and frees the old one, which another
+        // list in the same pool can reuse -- no `clear_all` needed. A
+        // `ListRef` copied out before the growing push keeps pointing at
+        // the old (now reused) slots instead of erroring out.
+        let mut pool: ListPool<i32> = ListPool::new();
+        let mut list = None;
+        for i in 0..4 {
+            ListRef::push(&mut list, i, &mut pool);
+        }
+        // The capacity-4 run is now full; copy the `ListRef` out before
+        // growing it further.
+        let stale = list.unwrap();
+
+        // Crosses the size-class boundary: relocates to an 8-slot run and
+        // frees the original 4-slot run.
+        ListRef::push(&mut list, 4, &mut pool);
+
+        // A second, unrelated list reuses the freed run.
+        let mut other = None;
+        for i in 100..104 {
+            ListRef::push(&mut other, i, &mut pool);
+        }
+
+        // The stale copy now aliases `other`'s data instead of failing.
+        assert_eq!(stale.get(0, &pool), Some(&100));
+        assert_ne!(stale.get(0, &pool), Some(&0));
+    }
+
+    #[test]
+    fn test_clear_all_resets_the_pool() {
+        let mut pool: ListPool<i32> = ListPool::new();
+        let mut list = None;
+        ListRef::push(&mut list, 1, &mut pool);
+
+        pool.clear_all();
+
+        assert_eq!(pool.pool.len(), 0);
+    }
+}